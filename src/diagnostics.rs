@@ -0,0 +1,72 @@
+//! # Diagnostics
+//! Source-span rendering, in the style of miette's fancy reports: an
+//! annotated, underlined snippet of the offending span alongside the
+//! failure message, instead of a bare "unexpected token".
+//!
+//! `Span` is a plain byte-offset range into the original source text. The
+//! intended consumer is `ParseError`: a parser records a `Span` as it
+//! consumes each token and threads it through so `report` has something to
+//! underline. `ParseError` itself lives in a module this tree doesn't carry,
+//! so that wiring is not yet done; `SpecificationError` borrows this
+//! module's `Span` type for its own `TokenToRuleAlreadyDefined.span` field,
+//! but since registration happens before any source text exists, that field
+//! is always `None` in this tree and `report` is never actually called on
+//! it.
+
+use std::ops::Range;
+
+/// A byte-offset range into the original source text.
+pub type Span = Range<usize>;
+
+/// Renders `message` as a caret/underline annotation under the text in
+/// `span`, looking up the surrounding line from `source`.
+pub fn report(source: &str, span: &Span, message: &str) -> String {
+    let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[span.end..]
+        .find('\n')
+        .map_or(source.len(), |i| span.end + i);
+    let line_no = source[..span.start].matches('\n').count() + 1;
+    let col = span.start - line_start + 1;
+    let underline_len = (span.end - span.start).max(1);
+
+    format!(
+        "error: {message}\n  --> line {line_no}, column {col}\n   |\n   | {line}\n   | {pad}{underline}\n",
+        message = message,
+        line_no = line_no,
+        col = col,
+        line = &source[line_start..line_end],
+        pad = " ".repeat(span.start - line_start),
+        underline = "^".repeat(underline_len),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn report_underlines_the_offending_span_on_its_own_line() {
+        let source = "let x = 1\nlet y = ;\n";
+        let span: Span = 18..18;
+
+        let rendered = report(source, &span, "expected expression");
+
+        assert_eq!(
+            rendered,
+            "error: expected expression\n  --> line 2, column 9\n   |\n   | let y = ;\n   |         ^\n"
+        );
+    }
+
+    #[test]
+    fn report_underlines_a_multi_byte_span_on_the_first_line() {
+        let source = "foo + bar";
+        let span: Span = 0..3;
+
+        let rendered = report(source, &span, "unknown identifier");
+
+        assert_eq!(
+            rendered,
+            "error: unknown identifier\n  --> line 1, column 1\n   |\n   | foo + bar\n   | ^^^\n"
+        );
+    }
+}
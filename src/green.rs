@@ -0,0 +1,510 @@
+//! # Green/red lossless syntax tree
+//! A lossless concrete-syntax-tree backend, in the style of rowan's
+//! green/red split. A `GreenNode` is an immutable, reference-counted record
+//! of a syntax kind and its children; structurally identical subtrees are
+//! deduplicated through a `NodeCache` so the many repeated leaves a Pratt
+//! parse produces (punctuation, keywords) are only allocated once.
+//!
+//! `GreenBuilder` drives tree construction from null/left denotation
+//! closures via `start_node`/`token`/`finish_node`; `GreenBuilderState`
+//! (the green-tree analogue of `restrictions::RestrictionsState`) is how a
+//! denotation holding only `&mut dyn Parser<T>` reaches the one builder
+//! shared across the whole parse, so every denotation's output lands in
+//! the same tree instead of each one returning an unrelated subtree of its
+//! own. The tree itself carries no parent pointers or absolute offsets,
+//! since those would make every node unshareable; `RedNode`/`RedToken` add
+//! both lazily as a cursor layer over a finished green tree, so the payoff
+//! -- full-fidelity round-trip of source text, including whitespace and
+//! comments -- is available without paying for it on the green tree
+//! itself.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Anything that can tag a green node or token with a syntax kind.
+pub trait SyntaxKind: Copy + Eq + Hash + fmt::Debug {}
+
+impl<K: Copy + Eq + Hash + fmt::Debug> SyntaxKind for K {}
+
+#[derive(Debug)]
+struct GreenTokenData<K> {
+    kind: K,
+    text: Box<str>,
+}
+
+/// An immutable leaf of a green tree: a kind plus its exact source text.
+#[derive(Debug, Clone)]
+pub struct GreenToken<K>(Arc<GreenTokenData<K>>);
+
+impl<K: SyntaxKind> GreenToken<K> {
+    fn new(kind: K, text: Box<str>) -> Self {
+        GreenToken(Arc::new(GreenTokenData { kind, text }))
+    }
+
+    pub fn kind(&self) -> K {
+        self.0.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.0.text
+    }
+
+    pub fn text_len(&self) -> usize {
+        self.0.text.len()
+    }
+}
+
+impl<K: SyntaxKind> PartialEq for GreenToken<K> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+            || (self.0.kind == other.0.kind && self.0.text == other.0.text)
+    }
+}
+
+impl<K: SyntaxKind> Eq for GreenToken<K> {}
+
+impl<K: SyntaxKind> Hash for GreenToken<K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.kind.hash(state);
+        self.0.text.hash(state);
+    }
+}
+
+/// A child of a `GreenNode`: either a nested node or a leaf token.
+#[derive(Debug, Clone)]
+pub enum GreenElement<K> {
+    Node(GreenNode<K>),
+    Token(GreenToken<K>),
+}
+
+impl<K: SyntaxKind> GreenElement<K> {
+    pub fn text_len(&self) -> usize {
+        match self {
+            GreenElement::Node(n) => n.text_len(),
+            GreenElement::Token(t) => t.text_len(),
+        }
+    }
+}
+
+impl<K: SyntaxKind> PartialEq for GreenElement<K> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (GreenElement::Node(a), GreenElement::Node(b)) => a == b,
+            (GreenElement::Token(a), GreenElement::Token(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl<K: SyntaxKind> Eq for GreenElement<K> {}
+
+impl<K: SyntaxKind> Hash for GreenElement<K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            GreenElement::Node(n) => n.hash(state),
+            GreenElement::Token(t) => t.hash(state),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct GreenNodeData<K> {
+    kind: K,
+    children: Vec<GreenElement<K>>,
+    text_len: usize,
+}
+
+/// An immutable, atomically-reference-counted node in the lossless tree.
+///
+/// Nodes produced through the same `NodeCache` share one allocation when
+/// they are structurally equal, so e.g. every `+` token in a parse is the
+/// same `Arc` rather than a fresh allocation per occurrence.
+#[derive(Debug, Clone)]
+pub struct GreenNode<K>(Arc<GreenNodeData<K>>);
+
+impl<K: SyntaxKind> GreenNode<K> {
+    fn new(kind: K, children: Vec<GreenElement<K>>) -> Self {
+        let text_len = children.iter().map(GreenElement::text_len).sum();
+        GreenNode(Arc::new(GreenNodeData {
+            kind,
+            children,
+            text_len,
+        }))
+    }
+
+    pub fn kind(&self) -> K {
+        self.0.kind
+    }
+
+    pub fn children(&self) -> &[GreenElement<K>] {
+        &self.0.children
+    }
+
+    pub fn text_len(&self) -> usize {
+        self.0.text_len
+    }
+}
+
+impl<K: SyntaxKind> PartialEq for GreenNode<K> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+            || (self.0.kind == other.0.kind && self.0.children == other.0.children)
+    }
+}
+
+impl<K: SyntaxKind> Eq for GreenNode<K> {}
+
+impl<K: SyntaxKind> Hash for GreenNode<K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.kind.hash(state);
+        for child in &self.0.children {
+            child.hash(state);
+        }
+    }
+}
+
+fn bucket_hash(kind: impl Hash, payload: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    kind.hash(&mut hasher);
+    payload.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Structural deduplication cache for green nodes and tokens.
+///
+/// Nodes are hashed and compared by `(kind, children)`, tokens by
+/// `(kind, text)`; a cache hit returns a clone of the already-allocated
+/// `Arc` instead of building a new one. Keyed by the raw hash rather than by
+/// a `(K, Vec<GreenElement<K>>)`/`(K, Box<str>)` map key, so probing a
+/// cache hit never clones `children`/`text` just to throw the clone away --
+/// only a genuine miss pays for the allocation that builds the new node.
+pub struct NodeCache<K: SyntaxKind> {
+    nodes: HashMap<u64, Vec<GreenNode<K>>>,
+    tokens: HashMap<u64, Vec<GreenToken<K>>>,
+}
+
+impl<K: SyntaxKind> Default for NodeCache<K> {
+    fn default() -> Self {
+        NodeCache {
+            nodes: HashMap::new(),
+            tokens: HashMap::new(),
+        }
+    }
+}
+
+impl<K: SyntaxKind> NodeCache<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn token(&mut self, kind: K, text: impl Into<Box<str>>) -> GreenToken<K> {
+        let text = text.into();
+        let hash = bucket_hash(kind, &text);
+        let bucket = self.tokens.entry(hash).or_insert_with(Vec::new);
+        if let Some(existing) = bucket
+            .iter()
+            .find(|existing| existing.kind() == kind && existing.text() == &*text)
+        {
+            return existing.clone();
+        }
+        let token = GreenToken::new(kind, text);
+        bucket.push(token.clone());
+        token
+    }
+
+    pub fn node(&mut self, kind: K, children: Vec<GreenElement<K>>) -> GreenNode<K> {
+        let hash = bucket_hash(kind, &children);
+        let bucket = self.nodes.entry(hash).or_insert_with(Vec::new);
+        if let Some(existing) = bucket
+            .iter()
+            .find(|existing| existing.kind() == kind && existing.children() == children.as_slice())
+        {
+            return existing.clone();
+        }
+        let node = GreenNode::new(kind, children);
+        bucket.push(node.clone());
+        node
+    }
+}
+
+/// Drives green-tree construction from the null/left denotation closures.
+///
+/// `start_node`/`finish_node` delimit a subtree and `token` appends a leaf;
+/// the builder tracks an explicit stack of in-progress children so
+/// denotations never have to assemble trees by hand.
+pub struct GreenBuilder<K: SyntaxKind> {
+    cache: NodeCache<K>,
+    parents: Vec<(K, Vec<GreenElement<K>>)>,
+    finished: Vec<GreenElement<K>>,
+}
+
+impl<K: SyntaxKind> Default for GreenBuilder<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: SyntaxKind> GreenBuilder<K> {
+    pub fn new() -> Self {
+        GreenBuilder {
+            cache: NodeCache::new(),
+            parents: Vec::new(),
+            finished: Vec::new(),
+        }
+    }
+
+    pub fn start_node(&mut self, kind: K) {
+        self.parents.push((kind, Vec::new()));
+    }
+
+    pub fn token(&mut self, kind: K, text: impl Into<Box<str>>) {
+        let token = self.cache.token(kind, text);
+        self.push(GreenElement::Token(token));
+    }
+
+    pub fn finish_node(&mut self) {
+        let (kind, children) = self
+            .parents
+            .pop()
+            .expect("finish_node called without a matching start_node");
+        let node = self.cache.node(kind, children);
+        self.push(GreenElement::Node(node));
+    }
+
+    fn push(&mut self, element: GreenElement<K>) {
+        match self.parents.last_mut() {
+            Some((_, children)) => children.push(element),
+            None => self.finished.push(element),
+        }
+    }
+
+    /// Finishes the builder, returning the single root node it produced.
+    ///
+    /// Panics if a `start_node` was never matched by a `finish_node`, or if
+    /// the builder did not produce exactly one root element.
+    pub fn finish(mut self) -> GreenNode<K> {
+        assert!(
+            self.parents.is_empty(),
+            "GreenBuilder finished with an unclosed start_node"
+        );
+        assert_eq!(
+            self.finished.len(),
+            1,
+            "GreenBuilder must produce exactly one root node"
+        );
+        match self.finished.pop().unwrap() {
+            GreenElement::Node(node) => node,
+            GreenElement::Token(_) => panic!("GreenBuilder root must be a node, not a bare token"),
+        }
+    }
+}
+
+/// Implemented by `Parser` so a `NullDenotation`/`LeftDenotation` can reach
+/// the parser's shared `GreenBuilder` instead of assembling a subtree of
+/// its own -- the green-tree analogue of `RestrictionsState`, and kept to
+/// one non-generic method for the same reason: a `dyn Parser<T>` that
+/// implements it stays object-safe.
+///
+/// A denotation wired up this way is `fn(&mut dyn Parser<T>, T, u32)`,
+/// same as any other (per `spec.rs`'s doc comment), but drives
+/// `parser.green_builder().start_node(...)`/`token(...)`/`finish_node()`
+/// as it goes rather than returning a `Node` it built by hand. Every
+/// denotation in the grammar shares the one `GreenBuilder` -- and so the
+/// one `NodeCache` -- so repeated leaves (punctuation, keywords) across the
+/// whole parse are deduplicated, not just within a single denotation's own
+/// subtree.
+pub trait GreenBuilderState<K: SyntaxKind> {
+    fn green_builder(&mut self) -> &mut GreenBuilder<K>;
+}
+
+/// A lazily-constructed cursor over a green tree: the "red" half of the
+/// red/green split, adding a parent pointer and an absolute text range that
+/// the green tree itself deliberately omits so it can stay shareable.
+#[derive(Debug, Clone)]
+pub struct RedNode<K> {
+    green: GreenNode<K>,
+    parent: Option<Rc<RedNode<K>>>,
+    offset: usize,
+}
+
+impl<K: SyntaxKind> RedNode<K> {
+    /// Builds a red cursor rooted at `green`, starting at text offset 0.
+    pub fn new_root(green: GreenNode<K>) -> Rc<Self> {
+        Rc::new(RedNode {
+            green,
+            parent: None,
+            offset: 0,
+        })
+    }
+
+    pub fn green(&self) -> &GreenNode<K> {
+        &self.green
+    }
+
+    pub fn kind(&self) -> K {
+        self.green.kind()
+    }
+
+    pub fn parent(&self) -> Option<&Rc<RedNode<K>>> {
+        self.parent.as_ref()
+    }
+
+    pub fn text_range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.text_len()
+    }
+
+    /// Lazily materializes this node's red children, computing each
+    /// child's absolute offset from its preceding siblings.
+    pub fn children(self: &Rc<Self>) -> Vec<RedElement<K>> {
+        let mut offset = self.offset;
+        let mut out = Vec::with_capacity(self.green.children().len());
+        for child in self.green.children() {
+            let len = child.text_len();
+            out.push(match child {
+                GreenElement::Node(n) => RedElement::Node(Rc::new(RedNode {
+                    green: n.clone(),
+                    parent: Some(self.clone()),
+                    offset,
+                })),
+                GreenElement::Token(t) => RedElement::Token(RedToken {
+                    green: t.clone(),
+                    offset,
+                }),
+            });
+            offset += len;
+        }
+        out
+    }
+}
+
+/// A red-tree token: a green token paired with its absolute offset.
+#[derive(Debug, Clone)]
+pub struct RedToken<K> {
+    green: GreenToken<K>,
+    offset: usize,
+}
+
+impl<K: SyntaxKind> RedToken<K> {
+    pub fn kind(&self) -> K {
+        self.green.kind()
+    }
+
+    pub fn text(&self) -> &str {
+        self.green.text()
+    }
+
+    pub fn text_range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.text_len()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum RedElement<K> {
+    Node(Rc<RedNode<K>>),
+    Token(RedToken<K>),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+    enum TestKind {
+        Root,
+        Plus,
+        Num,
+    }
+
+    //Catch Send/Sync changes
+    #[test]
+    fn test_green_node_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<GreenNode<TestKind>>();
+    }
+
+    #[test]
+    fn test_green_node_sync() {
+        fn assert_sync<T: Sync>() {}
+        assert_sync::<GreenNode<TestKind>>();
+    }
+
+    #[test]
+    fn node_cache_deduplicates_identical_tokens() {
+        let mut cache = NodeCache::new();
+        let a = cache.token(TestKind::Plus, "+");
+        let b = cache.token(TestKind::Plus, "+");
+        assert_eq!(a, b);
+        assert!(Arc::ptr_eq(&a.0, &b.0));
+    }
+
+    #[test]
+    fn node_cache_deduplicates_structurally_identical_nodes() {
+        let mut cache = NodeCache::new();
+        let token = cache.token(TestKind::Plus, "+");
+        let a = cache.node(TestKind::Root, vec![GreenElement::Token(token.clone())]);
+        let b = cache.node(TestKind::Root, vec![GreenElement::Token(token)]);
+        assert_eq!(a, b);
+        assert!(Arc::ptr_eq(&a.0, &b.0));
+    }
+
+    #[test]
+    fn builder_produces_expected_shape() {
+        let mut builder = GreenBuilder::new();
+        builder.start_node(TestKind::Root);
+        builder.token(TestKind::Plus, "+");
+        builder.token(TestKind::Plus, "+");
+        builder.finish_node();
+        let root = builder.finish();
+        assert_eq!(root.kind(), TestKind::Root);
+        assert_eq!(root.text_len(), 2);
+    }
+
+    struct StubParser {
+        builder: GreenBuilder<TestKind>,
+    }
+
+    impl GreenBuilderState<TestKind> for StubParser {
+        fn green_builder(&mut self) -> &mut GreenBuilder<TestKind> {
+            &mut self.builder
+        }
+    }
+
+    // Shaped like a `NullDenotation<T, Node>`: takes the parser (generic
+    // here over any `GreenBuilderState`, standing in for `dyn Parser<T>`)
+    // and a token's text, and drives the shared builder instead of
+    // returning a `Node` it assembled itself.
+    fn null_num<P: GreenBuilderState<TestKind>>(parser: &mut P, text: &str) {
+        parser.green_builder().token(TestKind::Num, text);
+    }
+
+    // Shaped like a `LeftDenotation<T, Node>`: takes the parser, the
+    // already-parsed left-hand side (here just a marker, since the LHS was
+    // already pushed into the shared builder by `null_num`), and drives
+    // the right-hand side's `null_num` in turn.
+    fn left_plus<P: GreenBuilderState<TestKind>>(parser: &mut P, _lhs: (), rhs_text: &str) {
+        parser.green_builder().token(TestKind::Plus, "+");
+        null_num(parser, rhs_text);
+    }
+
+    #[test]
+    fn denotations_drive_the_shared_builder_to_produce_a_cst() {
+        let mut parser = StubParser {
+            builder: GreenBuilder::new(),
+        };
+
+        parser.green_builder().start_node(TestKind::Root);
+        null_num(&mut parser, "1");
+        left_plus(&mut parser, (), "2");
+        parser.green_builder().finish_node();
+
+        let root = parser.builder.finish();
+        assert_eq!(root.kind(), TestKind::Root);
+        assert_eq!(root.children().len(), 3);
+        assert_eq!(root.text_len(), 3);
+    }
+}
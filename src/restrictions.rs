@@ -0,0 +1,118 @@
+//! # Restrictions
+//! Parse-time context flags threaded through denotations.
+//!
+//! Some grammars are context-sensitive: an `if` header may need to forbid
+//! struct literals in its condition, a statement position may only accept
+//! certain expression forms, and so on. The closure signatures in
+//! `types.rs` -- `fn(&mut dyn Parser<T>, T, u32)` -- have no room to carry
+//! mode flags like this, so `Restrictions` lives on the parser state
+//! instead and is read/overridden through `RestrictionsState`.
+//!
+//! This mirrors the technique rustc's expression parser uses
+//! (`RESTRICTION_STMT_EXPR`, `RESTRICTION_NO_STRUCT_LITERAL` toggled via
+//! `with_res`) to scope a parsing mode to a subexpression and restore it
+//! afterwards.
+
+bitflags! {
+    pub struct Restrictions: u8 {
+        /// The parser is in a position expecting a bare statement-expression,
+        /// not a full expression.
+        const STMT_EXPR = 0b0000_0001;
+        /// Struct literals are not permitted in the current subexpression,
+        /// e.g. the condition of an `if` or `while` header.
+        const NO_STRUCT_LITERAL = 0b0000_0010;
+    }
+}
+
+impl Default for Restrictions {
+    fn default() -> Self {
+        Restrictions::empty()
+    }
+}
+
+/// Implemented by `Parser` so it has somewhere to keep the active
+/// restriction set. Kept deliberately small (two non-generic methods) so a
+/// `dyn Parser<T>` that implements it stays object-safe.
+pub trait RestrictionsState {
+    fn restrictions(&self) -> Restrictions;
+    fn set_restrictions(&mut self, restrictions: Restrictions);
+}
+
+/// Runs `f` with `restrictions` active on `p`, restoring the previous set
+/// afterwards regardless of how `f` returns, and handing back whatever `f`
+/// returns -- e.g. the subexpression a `NullDenotation` just parsed.
+///
+/// A `NullDenotation`/`LeftDenotation` only ever holds `p` as
+/// `&mut dyn Parser<T>` (per `types.rs`'s closure signatures), so this is a
+/// free function taking `p` as a plain `&mut P` rather than a method on
+/// `RestrictionsState` itself: a generic method with a `Self`-typed return
+/// can only be called on a `Self: Sized` receiver, which would rule out
+/// calling it through the trait object a denotation actually has. As a
+/// free function, `R` is resolved at the call site like any other generic
+/// function, and `P` can be instantiated directly as `dyn Parser<T>`
+/// (assuming `Parser<T>: RestrictionsState`, as `Parser` itself is expected
+/// to require) without needing trait-object upcasting.
+pub fn with_restrictions<P, R>(
+    p: &mut P,
+    restrictions: Restrictions,
+    f: impl FnOnce(&mut P) -> R,
+) -> R
+where
+    P: RestrictionsState + ?Sized,
+{
+    let previous = p.restrictions();
+    p.set_restrictions(restrictions);
+    let result = f(p);
+    p.set_restrictions(previous);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct StubParser {
+        restrictions: Restrictions,
+    }
+
+    impl RestrictionsState for StubParser {
+        fn restrictions(&self) -> Restrictions {
+            self.restrictions
+        }
+
+        fn set_restrictions(&mut self, restrictions: Restrictions) {
+            self.restrictions = restrictions;
+        }
+    }
+
+    #[test]
+    fn with_restrictions_returns_closure_result_and_restores_previous_flags() {
+        let mut parser = StubParser {
+            restrictions: Restrictions::STMT_EXPR,
+        };
+
+        let parsed = with_restrictions(&mut parser, Restrictions::NO_STRUCT_LITERAL, |p| {
+            assert_eq!(p.restrictions(), Restrictions::NO_STRUCT_LITERAL);
+            42
+        });
+
+        assert_eq!(parsed, 42);
+        assert_eq!(parser.restrictions(), Restrictions::STMT_EXPR);
+    }
+
+    #[test]
+    fn with_restrictions_is_callable_through_a_trait_object() {
+        let mut parser = StubParser {
+            restrictions: Restrictions::STMT_EXPR,
+        };
+        let dyn_parser: &mut dyn RestrictionsState = &mut parser;
+
+        let parsed = with_restrictions(dyn_parser, Restrictions::NO_STRUCT_LITERAL, |p| {
+            assert_eq!(p.restrictions(), Restrictions::NO_STRUCT_LITERAL);
+            42
+        });
+
+        assert_eq!(parsed, 42);
+        assert_eq!(parser.restrictions(), Restrictions::STMT_EXPR);
+    }
+}
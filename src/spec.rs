@@ -42,11 +42,24 @@
 //! token -> syntax rule is recorded. This means later attempts to reassign the
 //! token -> syntax rule mapping are cause an error.
 //!
+//! `rule` offers a single-entry alternative to calling `add_null_assoc`/
+//! `add_left_right_assoc` separately: give it a token, optional prefix/infix
+//! denotations and a `Precedence`, and it derives the raw `PrecedenceLevel`s
+//! for you.
+//!
+//! `add_infix` is the analogous helper for associativity: give it a
+//! `Fixity` and it derives `lbp`/`rbp` instead of requiring them hand-picked.
+//!
+//! `layer` relaxes the WriteOnce policy across specs rather than within one:
+//! it stacks a base spec underneath this one as a fallback, so a dialect can
+//! extend or selectively override a core grammar without copying it.
+//!
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::marker::{Send, Sync};
 use std::mem::{discriminant, Discriminant};
 
+use diagnostics::Span;
 use node::SimpleNode;
 use precedence::PrecedenceLevel;
 use token::Token;
@@ -55,16 +68,101 @@ use types::*;
 /// This currently only indicates if your specification attempts to assign
 /// more than one syntax rule to the same token, thus ending early before
 /// trying to debug a bad parse.
+///
+/// Registration happens before any source text exists to point into, so
+/// `TokenToRuleAlreadyDefined.span` is always `None` here; the field stays
+/// so a future caller that registers rules while walking a grammar source
+/// file (rather than from static Rust code, as every call site in this
+/// tree does) can populate it and hand it to `diagnostics::report` without
+/// an API break. `ParseError` itself lives in a module this tree doesn't
+/// carry, so threading a span through *that* type is still out of scope.
 #[derive(Clone, Debug, Fail)]
 pub enum SpecificationError<T: Token + Send + Sync + 'static> {
     #[fail(display = "{} token -> rule mapping was already defined", tk)]
-    TokenToRuleAlreadyDefined { tk: T },
+    TokenToRuleAlreadyDefined { tk: T, span: Option<Span> },
+    #[fail(display = "layering this spec would shadow a rule already defined in the base spec")]
+    RuleShadowed,
+    #[fail(
+        display = "{} has no adjacent precedence level to bind its {:?} fixity against",
+        tk, fixity
+    )]
+    PrecedenceOutOfRange { tk: T, fixity: Fixity },
+    #[fail(
+        display = "{} is non-associative and cannot be chained at the same precedence level",
+        tk
+    )]
+    NonAssociativeChaining { tk: T },
+}
+
+/// Controls how `ParserSpec::layer` treats a token that both this spec and
+/// the base spec being layered underneath already define a rule for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShadowMode {
+    /// The higher-priority spec's rule silently wins.
+    Override,
+    /// Shadowing a base rule is rejected as a `SpecificationError`.
+    Error,
+}
+
+/// Ordered precedence tiers for the declarative `rule` registration API.
+///
+/// Each variant is a binding-power tier, loosest (`None`) to tightest
+/// (`Primary`); `next` returns the tier one step tighter, which is exactly
+/// what a left-associative infix rule wants as its right binding power.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Precedence {
+    None,
+    Assignment,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Call,
+    Primary,
+}
+
+impl Precedence {
+    /// Returns the next-tighter precedence tier, saturating at `Primary`.
+    pub fn next(self) -> Precedence {
+        use self::Precedence::*;
+        match self {
+            None => Assignment,
+            Assignment => Or,
+            Or => And,
+            And => Equality,
+            Equality => Comparison,
+            Comparison => Term,
+            Term => Factor,
+            Factor => Unary,
+            Unary => Call,
+            Call | Primary => Primary,
+        }
+    }
+}
+
+/// Associativity of an infix operator, used by `add_infix` to derive its
+/// left/right binding powers instead of requiring the caller to hand-pick
+/// `lbp`/`rbp`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fixity {
+    /// Binds one tighter on the right: `(bp, bp + 1)`.
+    Left,
+    /// Binds one looser on the right: `(bp, bp - 1)`.
+    Right,
+    /// Binds equally on both sides, `(bp, bp)`, and additionally marks the
+    /// token so a second use at the same level is rejected at parse time.
+    NonAssoc,
 }
 
 #[derive(Clone)]
 pub struct ParserSpec<T: Token + Send + Sync + 'static, Node = SimpleNode<T>> {
     null_map: HashMap<Discriminant<T>, NullInfo<T, Node>>,
     left_map: HashMap<Discriminant<T>, LeftInfo<T, Node>>,
+    non_assoc: HashSet<Discriminant<T>>,
+    fallbacks: Vec<ParserSpec<T, Node>>,
 }
 
 impl<T: Token + Send + Sync + 'static, Node> Default for ParserSpec<T, Node> {
@@ -78,7 +176,195 @@ impl<T: Token + Send + Sync + 'static, Node> ParserSpec<T, Node> {
         ParserSpec {
             null_map: HashMap::new(),
             left_map: HashMap::new(),
+            non_assoc: HashSet::new(),
+            fallbacks: Vec::new(),
+        }
+    }
+
+    /// Pushes `base` underneath this spec as a fallback layer: a lookup
+    /// that finds no rule for a token in this spec (or any fallback
+    /// layered on top of `base`) falls through to `base`'s own rules, in
+    /// priority order -- analogous to an l10n source registry trying each
+    /// source in turn.
+    ///
+    /// `mode` controls what happens when this spec already defines a rule
+    /// for a token `base` also defines: `ShadowMode::Override` lets the
+    /// shadow win silently (the existing strict "WriteOnce" behavior stays
+    /// available by choosing `ShadowMode::Error` instead, which rejects the
+    /// composition up front).
+    pub fn layer(
+        mut self,
+        base: ParserSpec<T, Node>,
+        mode: ShadowMode,
+    ) -> Result<Self, SpecificationError<T>> {
+        if mode == ShadowMode::Error {
+            // `self` may itself have fallbacks layered in already, and
+            // `base` may have its own fallbacks underneath it too, so a
+            // shadow can hide on either side behind a layer that isn't
+            // either spec's own direct map -- gather every token `self`
+            // can already resolve (through its whole chain) and check each
+            // one against `base.resolve_*`, which walks `base`'s whole
+            // chain in turn.
+            let mut self_null_discs = HashSet::new();
+            self.collect_null_discs(&mut self_null_discs);
+            let mut self_left_discs = HashSet::new();
+            self.collect_left_discs(&mut self_left_discs);
+
+            let shadows_null = self_null_discs
+                .iter()
+                .any(|disc| base.resolve_null(disc).is_some());
+            let shadows_left = self_left_discs
+                .iter()
+                .any(|disc| base.resolve_left(disc).is_some());
+            if shadows_null || shadows_left {
+                return Err(SpecificationError::RuleShadowed);
+            }
+        }
+        self.fallbacks.push(base);
+        Ok(self)
+    }
+
+    /// Collects every token `self` has a prefix rule for, recursing through
+    /// `self.fallbacks` so a rule reachable only via a nested fallback layer
+    /// still counts.
+    fn collect_null_discs(&self, out: &mut HashSet<Discriminant<T>>) {
+        out.extend(self.null_map.keys().copied());
+        for base in &self.fallbacks {
+            base.collect_null_discs(out);
+        }
+    }
+
+    /// Collects every token `self` has an infix rule for, recursing through
+    /// `self.fallbacks` so a rule reachable only via a nested fallback layer
+    /// still counts.
+    fn collect_left_discs(&self, out: &mut HashSet<Discriminant<T>>) {
+        out.extend(self.left_map.keys().copied());
+        for base in &self.fallbacks {
+            base.collect_left_discs(out);
+        }
+    }
+
+    /// Looks up the prefix rule for `disc`, trying this spec first and then
+    /// each fallback layer in the order it was layered on, short-circuiting
+    /// on the first hit.
+    pub fn resolve_null(&self, disc: &Discriminant<T>) -> Option<&NullInfo<T, Node>> {
+        self.null_map.get(disc).or_else(|| {
+            self.fallbacks
+                .iter()
+                .find_map(|base| base.resolve_null(disc))
+        })
+    }
+
+    /// Looks up the infix rule for `disc`, trying this spec first and then
+    /// each fallback layer in the order it was layered on, short-circuiting
+    /// on the first hit.
+    pub fn resolve_left(&self, disc: &Discriminant<T>) -> Option<&LeftInfo<T, Node>> {
+        self.left_map.get(disc).or_else(|| {
+            self.fallbacks
+                .iter()
+                .find_map(|base| base.resolve_left(disc))
+        })
+    }
+
+    /// Registers an infix rule for `token`, deriving its left/right binding
+    /// powers from `fixity` rather than requiring the caller to reverse-
+    /// engineer the off-by-one, mirroring the `Fixity`/`AssocOp` split
+    /// rustc's expression parser uses for the same purpose.
+    #[allow(clippy::map_entry)] // see add_left_assoc below
+    pub fn add_infix(
+        &mut self,
+        token: impl Into<T>,
+        bp: PrecedenceLevel,
+        fixity: Fixity,
+        func: LeftDenotation<T, Node>,
+    ) -> Result<(), SpecificationError<T>> {
+        let token = token.into();
+        let disc = discriminant(&token);
+        if self.left_map.contains_key(&disc) {
+            return Err(SpecificationError::TokenToRuleAlreadyDefined {
+                tk: token,
+                span: None,
+            });
+        }
+        let rbp = match fixity {
+            Fixity::Left => u32::from(bp).checked_add(1).ok_or_else(|| {
+                SpecificationError::PrecedenceOutOfRange {
+                    tk: token.clone(),
+                    fixity,
+                }
+            })?,
+            Fixity::Right => u32::from(bp).checked_sub(1).ok_or_else(|| {
+                SpecificationError::PrecedenceOutOfRange {
+                    tk: token.clone(),
+                    fixity,
+                }
+            })?,
+            Fixity::NonAssoc => u32::from(bp),
+        };
+        if fixity == Fixity::NonAssoc {
+            self.non_assoc.insert(disc);
+        }
+        self.left_map
+            .insert(disc, (bp, PrecedenceLevel::from(rbp), func));
+        Ok(())
+    }
+
+    /// Iterator-accepting counterpart of `add_infix`.
+    pub fn add_infixes(
+        &mut self,
+        tokens: impl IntoIterator<Item = impl Into<T>>,
+        bp: PrecedenceLevel,
+        fixity: Fixity,
+        func: LeftDenotation<T, Node>,
+    ) -> Result<(), SpecificationError<T>> {
+        for token in tokens {
+            self.add_infix(token, bp, fixity, func)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether `disc` was registered via `add_infix` with
+    /// `Fixity::NonAssoc`, meaning a second use of the token at the same
+    /// level should be rejected at parse time.
+    pub fn is_non_assoc(&self, disc: &Discriminant<T>) -> bool {
+        self.non_assoc.contains(disc)
+    }
+
+    /// Builds the error a `Parser` should raise on encountering a second use
+    /// of `token` at the same level as an already-parsed non-associative
+    /// operator with the same discriminant -- the "rejected at parse time"
+    /// half of `Fixity::NonAssoc`, gated on `is_non_assoc` since this spec
+    /// has no parser loop of its own to call it from automatically.
+    pub fn non_assoc_error(&self, token: T) -> SpecificationError<T> {
+        SpecificationError::NonAssociativeChaining { tk: token }
+    }
+
+    /// Registers both halves of a grammar rule for `token` in one call,
+    /// deriving binding powers from `prec` instead of requiring the caller
+    /// to juggle raw `PrecedenceLevel`s across two separate maps.
+    ///
+    /// The prefix half, if given, is stored as-is under `prec`; the infix
+    /// half, if given, is stored left-associative using `prec` as the left
+    /// binding power and `prec.next()` as the right binding power, so a
+    /// standard left-associative operator falls out without the caller
+    /// computing a right binding power by hand.
+    pub fn rule(
+        &mut self,
+        token: impl Into<T>,
+        prefix: Option<NullDenotation<T, Node>>,
+        infix: Option<LeftDenotation<T, Node>>,
+        prec: Precedence,
+    ) -> Result<(), SpecificationError<T>> {
+        let token = token.into();
+        let bp = PrecedenceLevel::from(prec as u32);
+        if let Some(func) = prefix {
+            self.add_null_assoc(token.clone(), bp, func)?;
+        }
+        if let Some(func) = infix {
+            let rbp = PrecedenceLevel::from(prec.next() as u32);
+            self.add_left_right_assoc(token, bp, rbp, func)?;
         }
+        Ok(())
     }
 
     pub fn add_null_assoc(
@@ -91,7 +377,10 @@ impl<T: Token + Send + Sync + 'static, Node> ParserSpec<T, Node> {
         let disc = discriminant(&token);
         match self.null_map.entry(disc) {
             std::collections::hash_map::Entry::Occupied(_) => {
-                Err(SpecificationError::TokenToRuleAlreadyDefined { tk: token })
+                Err(SpecificationError::TokenToRuleAlreadyDefined {
+                    tk: token,
+                    span: None,
+                })
             }
             p => {
                 p.or_insert((bp, func));
@@ -113,7 +402,10 @@ impl<T: Token + Send + Sync + 'static, Node> ParserSpec<T, Node> {
             self.left_map.insert(disc, (bp, bp, func));
             Ok(())
         } else {
-            Err(SpecificationError::TokenToRuleAlreadyDefined { tk: token })
+            Err(SpecificationError::TokenToRuleAlreadyDefined {
+                tk: token,
+                span: None,
+            })
         }
     }
 
@@ -131,7 +423,10 @@ impl<T: Token + Send + Sync + 'static, Node> ParserSpec<T, Node> {
             self.left_map.insert(disc, (lbp, rbp, func));
             Ok(())
         } else {
-            Err(SpecificationError::TokenToRuleAlreadyDefined { tk: token })
+            Err(SpecificationError::TokenToRuleAlreadyDefined {
+                tk: token,
+                span: None,
+            })
         }
     }
 
@@ -175,6 +470,10 @@ impl<T: Token + Send + Sync + 'static, Node> ParserSpec<T, Node> {
     ///Consumes a spec and gets the HashMaps used for mapping tokens
     /// to syntax rules. This avoids clones and allocations/deallocations
     /// of potentially large HashMaps when creating a Parser from the maps.
+    ///
+    /// Any layered fallbacks are flattened in here too, lowest-priority
+    /// first, so rules defined directly on this spec (or a higher-priority
+    /// fallback) always win over ones from a lower-priority fallback.
     #[allow(clippy::type_complexity)]
     pub fn maps(
         self,
@@ -182,7 +481,16 @@ impl<T: Token + Send + Sync + 'static, Node> ParserSpec<T, Node> {
         HashMap<Discriminant<T>, NullInfo<T, Node>>,
         HashMap<Discriminant<T>, LeftInfo<T, Node>>,
     ) {
-        (self.null_map, self.left_map)
+        let mut null_map = HashMap::new();
+        let mut left_map = HashMap::new();
+        for base in self.fallbacks.into_iter().rev() {
+            let (base_null, base_left) = base.maps();
+            null_map.extend(base_null);
+            left_map.extend(base_left);
+        }
+        null_map.extend(self.null_map);
+        left_map.extend(self.left_map);
+        (null_map, left_map)
     }
 }
 
@@ -201,4 +509,117 @@ mod test {
         fn assert_sync<T: Sync>() {}
         assert_sync::<ParserSpec<String>>();
     }
+
+    fn stub_null() -> NullDenotation<String> {
+        |_parser, _tok, _bp| unimplemented!("never called, only registered")
+    }
+
+    fn stub_left() -> LeftDenotation<String> {
+        |_parser, _tok, _bp, _left| unimplemented!("never called, only registered")
+    }
+
+    #[test]
+    fn precedence_next_is_strictly_ordered_and_saturates_at_primary() {
+        let mut prec = Precedence::None;
+        while prec != Precedence::Primary {
+            let next = prec.next();
+            assert!(next > prec);
+            prec = next;
+        }
+        assert_eq!(Precedence::Primary.next(), Precedence::Primary);
+    }
+
+    #[test]
+    fn rule_registers_prefix_and_infix_halves() {
+        let mut spec = ParserSpec::<String>::new();
+        spec.rule(
+            "-".to_string(),
+            Some(stub_null()),
+            Some(stub_left()),
+            Precedence::Term,
+        )
+        .unwrap();
+
+        let disc = discriminant(&"-".to_string());
+        assert!(spec.null_map.contains_key(&disc));
+        assert!(spec.left_map.contains_key(&disc));
+    }
+
+    #[test]
+    fn add_infix_right_fixity_errors_instead_of_underflowing_at_lowest_precedence() {
+        let mut spec = ParserSpec::<String>::new();
+        let lowest = PrecedenceLevel::from(Precedence::None as u32);
+        assert!(spec
+            .add_infix("=".to_string(), lowest, Fixity::Right, stub_left())
+            .is_err());
+    }
+
+    #[test]
+    fn add_infix_marks_non_assoc_tokens_and_non_assoc_error_reports_it() {
+        let mut spec = ParserSpec::<String>::new();
+        let bp = PrecedenceLevel::from(Precedence::Comparison as u32);
+        spec.add_infix("<".to_string(), bp, Fixity::NonAssoc, stub_left())
+            .unwrap();
+
+        let disc = discriminant(&"<".to_string());
+        assert!(spec.is_non_assoc(&disc));
+
+        match spec.non_assoc_error("<".to_string()) {
+            SpecificationError::NonAssociativeChaining { .. } => {}
+            other => panic!("expected NonAssociativeChaining, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn layer_resolves_fallback_through_multiple_levels() {
+        let mut base = ParserSpec::<String>::new();
+        base.add_null_assoc("+".to_string(), PrecedenceLevel::from(1), stub_null())
+            .unwrap();
+        let middle = ParserSpec::<String>::new()
+            .layer(base, ShadowMode::Override)
+            .unwrap();
+        let top = ParserSpec::<String>::new()
+            .layer(middle, ShadowMode::Override)
+            .unwrap();
+
+        let disc = discriminant(&"+".to_string());
+        assert!(top.resolve_null(&disc).is_some());
+
+        let (null_map, _) = top.maps();
+        assert!(null_map.contains_key(&disc));
+    }
+
+    #[test]
+    fn layer_error_mode_detects_shadowing_reachable_through_nested_fallbacks() {
+        let mut base = ParserSpec::<String>::new();
+        base.add_null_assoc("+".to_string(), PrecedenceLevel::from(1), stub_null())
+            .unwrap();
+        let middle = ParserSpec::<String>::new()
+            .layer(base, ShadowMode::Override)
+            .unwrap();
+
+        let mut top = ParserSpec::<String>::new();
+        top.add_null_assoc("+".to_string(), PrecedenceLevel::from(2), stub_null())
+            .unwrap();
+
+        assert!(top.layer(middle, ShadowMode::Error).is_err());
+    }
+
+    #[test]
+    fn layer_error_mode_detects_shadowing_of_a_rule_already_reachable_through_self_fallbacks() {
+        let mut middle_with_plus = ParserSpec::<String>::new();
+        middle_with_plus
+            .add_null_assoc("+".to_string(), PrecedenceLevel::from(1), stub_null())
+            .unwrap();
+        let top = ParserSpec::<String>::new()
+            .layer(middle_with_plus, ShadowMode::Override)
+            .unwrap();
+
+        let mut base_with_plus = ParserSpec::<String>::new();
+        base_with_plus
+            .add_null_assoc("+".to_string(), PrecedenceLevel::from(2), stub_null())
+            .unwrap();
+
+        assert!(top.layer(base_with_plus, ShadowMode::Error).is_err());
+    }
 }